@@ -1,12 +1,19 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use csv::Writer;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
 use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 use std::{env, fs::read_dir};
 
 use semver::Version;
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, from_str};
+use sha1::{Digest, Sha1};
+
+const CACHE_FILE_NAME: &str = ".nand-cache.json";
 
 const BUILTIN_CHIPS: [&str; 18] = [
     // Merge / Split
@@ -42,70 +49,277 @@ macro_rules! path {
     }};
 }
 
+/// Digital Logic Sim's Steam AppID, used to locate its Proton compat prefix.
+const DLS_APPID: &str = "1219170";
+
+/// Upper bound on chip nesting depth; guards against pathologically deep
+/// (but acyclic) chip hierarchies causing unbounded recursion.
+const MAX_CHIP_DEPTH: usize = 256;
+
+fn default_save_dir_for_current_os() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        return Some(path!(
+            env::var("USERPROFILE").ok()?,
+            "AppData",
+            "LocalLow",
+            "SebastianLague",
+            "Digital-Logic-Sim"
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        return Some(path!(
+            env::var("HOME").ok()?,
+            ".config",
+            "unity3d",
+            "SebastianLague",
+            "Digital-Logic-Sim"
+        ));
+    }
+    // Might not work if app is not fully installed
+    #[cfg(target_os = "macos")]
+    {
+        return Some(path!(
+            env::var("HOME").ok()?,
+            "Library",
+            "Application Support",
+            "unity3d",
+            "SebastianLague",
+            "Digital-Logic-Sim"
+        ));
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
+/// A parsed node from Valve's text VDF format: either a leaf string value or
+/// a nested `{ }` block of further key/value pairs.
+#[derive(Debug, Clone)]
+enum VdfValue {
+    Str(String),
+    Obj(HashMap<String, VdfValue>),
+}
+
+fn parse_vdf(input: &str) -> Option<VdfValue> {
+    let mut chars = input.chars().peekable();
+    parse_vdf_obj(&mut chars)
+}
+
+fn parse_vdf_obj(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<VdfValue> {
+    let mut entries = HashMap::new();
+
+    loop {
+        skip_vdf_whitespace(chars);
+        match chars.peek() {
+            None => return Some(VdfValue::Obj(entries)),
+            Some('}') => {
+                chars.next();
+                return Some(VdfValue::Obj(entries));
+            }
+            Some('"') => {
+                let key = read_vdf_string(chars)?;
+                skip_vdf_whitespace(chars);
+                match chars.peek() {
+                    Some('"') => {
+                        let value = read_vdf_string(chars)?;
+                        entries.insert(key, VdfValue::Str(value));
+                    }
+                    Some('{') => {
+                        chars.next();
+                        entries.insert(key, parse_vdf_obj(chars)?);
+                    }
+                    _ => return None,
+                }
+            }
+            Some(_) => {
+                chars.next();
+            }
+        }
+    }
+}
+
+fn skip_vdf_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn read_vdf_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    if chars.next() != Some('"') {
+        return None;
+    }
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "windows")]
+fn windows_steam_root() -> Option<PathBuf> {
+    use winreg::RegKey;
+    use winreg::enums::HKEY_CURRENT_USER;
+
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let steam_key = hkcu.open_subkey("SOFTWARE\\Valve\\Steam").ok()?;
+    let steam_path: String = steam_key.get_value("SteamPath").ok()?;
+    Some(PathBuf::from(steam_path))
+}
+
+fn steam_root() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        return windows_steam_root();
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let home = env::var("HOME").ok()?;
+        return Some(path!(home, ".steam", "steam"));
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Enumerates every Steam library root: the main Steam install plus any
+/// additional libraries listed in `steamapps/libraryfolders.vdf`.
+fn steam_library_folders() -> Vec<PathBuf> {
+    let Some(root) = steam_root() else {
+        return Vec::new();
+    };
+
+    let mut libraries = vec![root.clone()];
+
+    let vdf_path = path!(&root, "steamapps", "libraryfolders.vdf");
+    let Ok(contents) = std::fs::read_to_string(&vdf_path) else {
+        return libraries;
+    };
+    let Some(VdfValue::Obj(vdf_root)) = parse_vdf(&contents) else {
+        return libraries;
+    };
+    let Some(VdfValue::Obj(folders)) = vdf_root.get("libraryfolders") else {
+        return libraries;
+    };
+
+    for value in folders.values() {
+        if let VdfValue::Obj(entry) = value {
+            if let Some(VdfValue::Str(path)) = entry.get("path") {
+                libraries.push(PathBuf::from(path));
+            }
+        }
+    }
+
+    libraries
+}
+
+/// Resolves the Digital Logic Sim save directory through Steam, checking
+/// the Proton compat prefix (for Linux/Steam Deck installs) under every
+/// known library root. If no Proton save directory is found (or Steam isn't
+/// installed), falls back to the native per-OS save directory when it
+/// exists, and to `None` otherwise.
+fn discover_dls_save_dir() -> Option<PathBuf> {
+    for library in steam_library_folders() {
+        let proton_save_dir = path!(
+            &library,
+            "steamapps",
+            "compatdata",
+            DLS_APPID,
+            "pfx",
+            "drive_c",
+            "users",
+            "steamuser",
+            "AppData",
+            "LocalLow",
+            "SebastianLague",
+            "Digital-Logic-Sim"
+        );
+        if proton_save_dir.exists() {
+            return Some(proton_save_dir);
+        }
+    }
+
+    default_save_dir_for_current_os().filter(|path| path.exists())
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Project {
     name: String,
     path: PathBuf,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[allow(non_snake_case)]
 struct Chip {
     NAND_count: usize,
     checked: bool,
 }
 
-impl Default for Chip {
-    fn default() -> Self {
-        Chip {
-            NAND_count: 0,
-            checked: false,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedChip {
+    sha1: String,
+    subchips: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ParseCache {
+    #[serde(default)]
+    entries: HashMap<String, CachedChip>,
+}
+
+impl ParseCache {
+    fn load(path: &PathBuf) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &PathBuf) {
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    eprintln!("[DEBUG] Failed to write parse cache to {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("[DEBUG] Failed to serialize parse cache: {}", e),
         }
     }
 }
 
+fn sha1_hex(data: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(data);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 struct ProjectManager {
     pub game_dir: PathBuf,
     pub projects: Vec<Project>,
+    cache: RefCell<ParseCache>,
 }
 
 impl ProjectManager {
     fn new(game_dir: Option<PathBuf>) -> Self {
         let mut sim = Self {
-            game_dir: game_dir.unwrap_or_else(|| PathBuf::new()),
+            game_dir: game_dir
+                .or_else(discover_dls_save_dir)
+                .or_else(default_save_dir_for_current_os)
+                .unwrap_or_default(),
             projects: Vec::new(),
+            cache: RefCell::new(ParseCache::default()),
         };
-        #[cfg(target_os = "windows")]
-        {
-            sim.game_dir = path!(
-                env::var("USERPROFILE").unwrap(),
-                "AppData",
-                "LocalLow",
-                "SebastianLague",
-                "Digital-Logic-Sim"
-            );
-        }
-        #[cfg(target_os = "linux")]
-        {
-            sim.game_dir = path!(
-                env::var("HOME").unwrap(),
-                ".config",
-                "unity3d",
-                "SebastianLague",
-                "Digital-Logic-Sim"
-            );
-        }
-        // Might not work if app is not fully installed
-        #[cfg(target_os = "macos")]
-        {
-            sim.game_dir = path!(
-                env::var("HOME").unwrap(),
-                "Library",
-                "Application Support",
-                "unity3d",
-                "SebastianLague",
-                "Digital-Logic-Sim"
-            );
-        }
 
         let projects_path = path!(&sim.game_dir, "Projects");
 
@@ -121,9 +335,74 @@ impl ProjectManager {
             .collect();
 
         sim.projects = projects.into_iter().filter(|p| p.path.is_dir()).collect();
+        sim.cache = RefCell::new(ParseCache::load(&sim.cache_path()));
         sim
     }
 
+    fn find_project(&self, name: &str) -> Option<&Project> {
+        self.projects.iter().find(|p| p.name == name)
+    }
+
+    fn cache_path(&self) -> PathBuf {
+        path!(&self.game_dir, CACHE_FILE_NAME)
+    }
+
+    fn save_cache(&self) {
+        self.cache.borrow().save(&self.cache_path());
+    }
+
+    fn collect_mtimes(&self, project: &Project) -> HashMap<PathBuf, SystemTime> {
+        let mut mtimes = HashMap::new();
+
+        let desc_path = path!(&project.path, "ProjectDescription.json");
+        if let Ok(modified) = std::fs::metadata(&desc_path).and_then(|m| m.modified()) {
+            mtimes.insert(desc_path, modified);
+        }
+
+        let chips_dir = path!(&project.path, "Chips");
+        if let Ok(entries) = read_dir(&chips_dir) {
+            for entry in entries.filter_map(Result::ok) {
+                if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                    mtimes.insert(entry.path(), modified);
+                }
+            }
+        }
+
+        mtimes
+    }
+
+    fn watch_project(&self, project: &Project, format: OutputFormat) {
+        let mut last_mtimes = self.collect_mtimes(project);
+
+        if let Some(result) = self.scan_project(project) {
+            result.render(format);
+        }
+
+        println!("Watching {} for changes (Ctrl+C to stop)...", project.name);
+
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+
+            let current_mtimes = self.collect_mtimes(project);
+            if current_mtimes != last_mtimes {
+                last_mtimes = current_mtimes;
+                if let Some(result) = self.scan_project(project) {
+                    result.render(format);
+                }
+            }
+        }
+    }
+
+    fn print_cache_status(&self) {
+        let cache_path = self.cache_path();
+        if cache_path.exists() {
+            println!("Cache file: {}", cache_path.display());
+            println!("Cached chips: {}", self.cache.borrow().entries.len());
+        } else {
+            println!("No cache file found at {}", cache_path.display());
+        }
+    }
+
     fn list_projects(&self) {
         println!("Choose a DLS Project to NAND scan:");
         let mut longest_name = 3;
@@ -264,6 +543,8 @@ impl ProjectManager {
 
         let total_nand = chip_map.values().map(|c| c.NAND_count).sum();
 
+        self.save_cache();
+
         Some(ProjectScanResult {
             project,
             chip_map,
@@ -276,6 +557,17 @@ impl ProjectManager {
         chip: &str,
         chip_map: &mut HashMap<String, Chip>,
         base_path: &PathBuf,
+    ) -> Result<(), String> {
+        let mut stack = Vec::new();
+        self.check_chip_inner(chip, chip_map, base_path, &mut stack)
+    }
+
+    fn check_chip_inner(
+        &self,
+        chip: &str,
+        chip_map: &mut HashMap<String, Chip>,
+        base_path: &PathBuf,
+        stack: &mut Vec<String>,
     ) -> Result<(), String> {
         if let Some(existing) = chip_map.get(chip) {
             if existing.checked {
@@ -283,43 +575,97 @@ impl ProjectManager {
             }
         }
 
+        if stack.len() > MAX_CHIP_DEPTH {
+            return Err(format!(
+                "Max chip nesting depth ({}) exceeded at {}",
+                MAX_CHIP_DEPTH, chip
+            ));
+        }
+
+        if let Some(start) = stack.iter().position(|name| name == chip) {
+            let cycle = stack[start..]
+                .iter()
+                .cloned()
+                .chain(std::iter::once(chip.to_string()))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(format!("Cycle detected in chip dependencies: {}", cycle));
+        }
+
+        stack.push(chip.to_string());
+
         let chip_path = path!(base_path, "Chips", format!("{}.json", chip));
         if !chip_path.exists() {
             return Err(format!("Chip file not found: {}", chip));
         }
 
-        let content = std::fs::read_to_string(&chip_path)
+        let content = std::fs::read(&chip_path)
             .map_err(|_| format!("Failed to read chip file for {}", chip))?;
-        let data: Value =
-            from_str(&content).map_err(|_| format!("Failed to parse JSON for {}", chip))?;
+        let hash = sha1_hex(&content);
+        let cache_key = chip_path.to_string_lossy().into_owned();
+
+        let cached = self
+            .cache
+            .borrow()
+            .entries
+            .get(&cache_key)
+            .filter(|entry| entry.sha1 == hash)
+            .map(|entry| entry.subchips.clone());
+
+        let subchip_names = match cached {
+            Some(names) => names,
+            None => {
+                let text = String::from_utf8_lossy(&content);
+                let data: Value =
+                    from_str(&text).map_err(|_| format!("Failed to parse JSON for {}", chip))?;
+
+                let subchips = data["SubChips"]
+                    .as_array()
+                    .ok_or_else(|| format!("SubChips missing or not array for {}", chip))?;
+
+                let names = subchips
+                    .iter()
+                    .map(|subchip| {
+                        subchip
+                            .get("Name")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string())
+                            .ok_or_else(|| format!("SubChip entry missing Name in {}", chip))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.cache.borrow_mut().entries.insert(
+                    cache_key,
+                    CachedChip {
+                        sha1: hash,
+                        subchips: names.clone(),
+                    },
+                );
 
-        let subchips = data["SubChips"]
-            .as_array()
-            .ok_or_else(|| format!("SubChips missing or not array for {}", chip))?;
+                names
+            }
+        };
 
         let mut nand_total = 0;
 
-        for subchip in subchips {
-            let name = subchip
-                .get("Name")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| format!("SubChip entry missing Name in {}", chip))?;
-
-            if !chip_map.contains_key(name) {
-                chip_map.insert(name.to_string(), Chip::default());
+        for name in &subchip_names {
+            if !chip_map.contains_key(name.as_str()) {
+                chip_map.insert(name.clone(), Chip::default());
             }
 
-            if !chip_map.get(name).unwrap().checked {
-                self.check_chip(name, chip_map, base_path)?;
+            if !chip_map.get(name.as_str()).unwrap().checked {
+                self.check_chip_inner(name, chip_map, base_path, stack)?;
             }
 
-            nand_total += chip_map.get(name).unwrap().NAND_count;
+            nand_total += chip_map.get(name.as_str()).unwrap().NAND_count;
         }
 
         let entry = chip_map.get_mut(chip).unwrap();
         entry.NAND_count = nand_total;
         entry.checked = true;
 
+        stack.pop();
+
         Ok(())
     }
 }
@@ -338,6 +684,7 @@ impl<'a> fmt::Display for ProjectScanResult<'a> {
             .filter(|(k, _)| !BUILTIN_CHIPS.contains(&k.as_str()))
             .collect();
         let longest_name = filtered_chip_map.keys().map(|s| s.len()).max().unwrap_or(0);
+        #[allow(non_snake_case)]
         let most_NAND = filtered_chip_map
             .values()
             .map(|c| c.NAND_count)
@@ -394,14 +741,151 @@ impl<'a> fmt::Display for ProjectScanResult<'a> {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct ChipReport {
+    name: String,
+    nand_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ScanReport {
+    project: String,
+    path: String,
+    total_nand: usize,
+    avg_nand: f64,
+    chips: Vec<ChipReport>,
+}
+
+impl<'a> ProjectScanResult<'a> {
+    fn to_report(&self) -> ScanReport {
+        let mut chips: Vec<ChipReport> = self
+            .chip_map
+            .iter()
+            .filter(|(k, _)| !BUILTIN_CHIPS.contains(&k.as_str()))
+            .map(|(name, chip)| ChipReport {
+                name: name.clone(),
+                nand_count: chip.NAND_count,
+            })
+            .collect();
+        chips.sort_by(|a, b| b.nand_count.cmp(&a.nand_count).then(a.name.cmp(&b.name)));
+
+        let avg_nand = if chips.is_empty() {
+            0.0
+        } else {
+            self.total_nand as f64 / chips.len() as f64
+        };
+
+        ScanReport {
+            project: self.project.name.clone(),
+            path: self.project.path.display().to_string(),
+            total_nand: self.total_nand,
+            avg_nand,
+            chips,
+        }
+    }
+
+    fn render(&self, format: OutputFormat) {
+        match format {
+            OutputFormat::Text => println!("{}", self),
+            OutputFormat::Json => match serde_json::to_string_pretty(&self.to_report()) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("[DEBUG] Failed to serialize report to JSON: {}", e),
+            },
+            OutputFormat::Csv => {
+                let report = self.to_report();
+                let write_result = (|| -> Result<(), csv::Error> {
+                    let mut writer = Writer::from_writer(io::stdout());
+                    writer.write_record(["chip", "nand_count", "total_nand", "avg_nand"])?;
+                    for chip in &report.chips {
+                        writer.write_record([
+                            chip.name.as_str(),
+                            &chip.nand_count.to_string(),
+                            &report.total_nand.to_string(),
+                            &report.avg_nand.to_string(),
+                        ])?;
+                    }
+                    writer.flush()?;
+                    Ok(())
+                })();
+                if let Err(e) = write_result {
+                    eprintln!("[DEBUG] Failed to write CSV report: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// List the available DLS projects
+    List,
+    /// Scan one project, or every project with --all
+    Scan {
+        /// Name of the project to scan
+        project: Option<String>,
+        /// Scan every known project instead of a single one
+        #[arg(long)]
+        all: bool,
+        /// Keep re-scanning as the project's files change on disk
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Show the status of the parse cache
+    ScanCache,
+}
+
 #[derive(Debug, Clone, Parser)]
 struct Args {
     /// optional Path to the game directory
+    #[arg(long)]
     game_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Output format for scan results
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    format: OutputFormat,
 }
 
 fn main() {
     let args = Args::parse();
     let manager = ProjectManager::new(args.game_dir);
-    manager.prompt_and_scan();
+
+    match args.command {
+        Some(Command::List) => manager.list_projects(),
+        Some(Command::Scan { project, all, watch }) => {
+            if all && watch {
+                eprintln!("--watch only supports scanning a single project, not --all");
+            } else if all {
+                for p in &manager.projects {
+                    if let Some(result) = manager.scan_project(p) {
+                        result.render(args.format);
+                    }
+                }
+            } else if let Some(name) = project {
+                match manager.find_project(&name) {
+                    Some(p) => {
+                        if watch {
+                            manager.watch_project(p, args.format);
+                        } else if let Some(result) = manager.scan_project(p) {
+                            result.render(args.format);
+                        }
+                    }
+                    None => eprintln!("No such project: {}", name),
+                }
+            } else {
+                eprintln!("Specify a project name or --all");
+            }
+        }
+        Some(Command::ScanCache) => manager.print_cache_status(),
+        None => manager.prompt_and_scan(),
+    }
 }